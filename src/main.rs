@@ -1,8 +1,600 @@
-use image::{imageops, ImageReader, RgbImage};
+use image::{imageops, ImageBuffer, ImageEncoder, ImageReader, Pixel, Rgb32FImage, RgbImage};
+use rayon::prelude::*;
 use std::env;
 use std::error::Error;
 use std::process;
 
+/// A resized source buffer at pixel depth `P` (`Rgb<u8>` or `Rgb<f32>`).
+type ResizedBuffer<P> = ImageBuffer<P, Vec<<P as Pixel>::Subpixel>>;
+/// A successfully loaded source's path paired with its resized buffer.
+type LoadedSource<P> = (String, ResizedBuffer<P>);
+/// A `LoadedSource` tagged with its original index, for sorting back into input order
+/// after loading off the thread pool.
+type IndexedSource<P> = (usize, String, ResizedBuffer<P>);
+
+/// Loads and Lanczos-resizes every source image to the target resolution, in parallel.
+///
+/// `to_buffer` converts a decoded `DynamicImage` into the pixel depth the caller wants
+/// (`to_rgb8` for the standard 8-bit path, `to_rgb32f` for the HDR path), so the same
+/// parallel loading logic serves both without duplicating the error handling below.
+/// Decoding runs on the pool via `par_iter`, but the strip-index mapping done later
+/// depends on input order, so each result is tagged with its original index and the
+/// indexed pairs are sorted back into place before being unwrapped. The path of each
+/// successfully loaded source is returned alongside its buffer so callers that need
+/// provenance (e.g. TIFF metadata) know exactly which inputs formed the strips.
+fn load_and_resize_sources<P, F>(
+    wallpaper_paths: &[String],
+    width: u32,
+    height: u32,
+    pool: &rayon::ThreadPool,
+    to_buffer: F,
+) -> Vec<LoadedSource<P>>
+where
+    P: Pixel + Send + Sync + 'static,
+    P::Subpixel: image::Primitive + Send + Sync + 'static,
+    F: Fn(image::DynamicImage) -> ResizedBuffer<P> + Sync,
+{
+    let mut indexed_images: Vec<IndexedSource<P>> = pool
+        .install(|| {
+            wallpaper_paths
+                .par_iter()
+                .enumerate()
+                .filter_map(|(index, path)| {
+                    println!("Loading and resizing: {}", path);
+
+                    // FIX: Use a nested match to handle different error types explicitly.
+                    // This correctly separates the `io::Error` from `ImageReader::open`
+                    // from the `ImageError` that can occur during decoding.
+                    match ImageReader::open(path) {
+                        Ok(reader) => {
+                            match reader.with_guessed_format() {
+                                Ok(guessed_reader) => match guessed_reader.decode() {
+                                    Ok(img) => {
+                                        // On success, convert to the target depth and resize.
+                                        let depth_img = to_buffer(img);
+                                        Some((
+                                            index,
+                                            path.clone(),
+                                            imageops::resize(
+                                                &depth_img,
+                                                width,
+                                                height,
+                                                imageops::FilterType::Lanczos3,
+                                            ),
+                                        ))
+                                    }
+                                    Err(e) => {
+                                        eprintln!("Warning: Skipping {} due to a decode error: {}", path, e);
+                                        None
+                                    }
+                                },
+                                Err(e) => {
+                                    eprintln!("Warning: Skipping {} due to a format error: {}", path, e);
+                                    None
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("Warning: Skipping {} due to an IO error: {}", path, e);
+                            None
+                        }
+                    }
+                })
+                .collect()
+        });
+    indexed_images.sort_by_key(|(index, _, _)| *index);
+    indexed_images
+        .into_iter()
+        .map(|(_, path, img)| (path, img))
+        .collect()
+}
+
+/// Composites the pre-resized sources using the angled mask described in
+/// `create_angled_strip_wallpaper`, writing each output row in parallel.
+///
+/// This is generic over the pixel type (`Rgb<u8>` for the standard path, `Rgb<f32>`
+/// for the HDR path) so the masking logic only has to be written once; only the
+/// subpixel type that flows through `get_pixel`/`from_raw` changes between callers.
+fn composite_angled_strips<P>(
+    width: u32,
+    height: u32,
+    tan_theta: f32,
+    min_skewed_x: f32,
+    skewed_range: f32,
+    resized_images: &[ResizedBuffer<P>],
+    pool: &rayon::ThreadPool,
+) -> Result<ResizedBuffer<P>, Box<dyn Error>>
+where
+    P: Pixel + Send + Sync,
+    P::Subpixel: Send + Sync,
+{
+    let num_users = resized_images.len();
+
+    let row_channels: Vec<Vec<P::Subpixel>> = pool.install(|| {
+        (0..height)
+            .into_par_iter()
+            .map(|y| {
+                let mut row = Vec::with_capacity(width as usize * P::CHANNEL_COUNT as usize);
+                for x in 0..width {
+                    let skewed_x = x as f32 - y as f32 * tan_theta;
+                    let normalized_progress = (skewed_x - min_skewed_x) / skewed_range;
+                    let image_index_float = normalized_progress * num_users as f32;
+                    let image_index = (image_index_float.floor() as usize).min(num_users - 1);
+
+                    let source_image = &resized_images[image_index];
+                    let pixel = source_image.get_pixel(x, y);
+                    row.extend_from_slice(pixel.channels());
+                }
+                row
+            })
+            .collect()
+    });
+
+    let mut buffer =
+        Vec::with_capacity(width as usize * height as usize * P::CHANNEL_COUNT as usize);
+    for row in row_channels {
+        buffer.extend(row);
+    }
+    ImageBuffer::from_raw(width, height, buffer)
+        .ok_or_else(|| "Failed to assemble composited image buffer".into())
+}
+
+/// Writes an `Rgb<f32>` canvas as an OpenEXR file, preserving full float precision.
+fn write_hdr_exr(output_path: &str, image: &Rgb32FImage) -> Result<(), Box<dyn Error>> {
+    let (width, height) = image.dimensions();
+    exr::prelude::write_rgb_file(output_path, width as usize, height as usize, |x, y| {
+        let pixel = image.get_pixel(x as u32, y as u32);
+        (pixel[0], pixel[1], pixel[2])
+    })?;
+    Ok(())
+}
+
+/// Writes an 8-bit RGB canvas as a TIFF, embedding provenance metadata into the IFD tags
+/// so a generated wallpaper is self-documenting: which angle and which ordered source
+/// images produced it.
+fn write_tiff_with_provenance(
+    output_path: &str,
+    image: &RgbImage,
+    angle_degrees: f32,
+    source_paths: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = image.dimensions();
+    let file = std::fs::File::create(output_path)?;
+    let mut tiff_encoder = tiff::encoder::TiffEncoder::new(file)?;
+    let mut tiff_image =
+        tiff_encoder.new_image::<tiff::encoder::colortype::RGB8>(width, height)?;
+
+    let description = format!(
+        "angle={:.3} degrees; resolution={}x{}; sources={}",
+        angle_degrees,
+        width,
+        height,
+        source_paths.join(",")
+    );
+    tiff_image.encoder().write_tag(tiff::tags::Tag::Artist, "dots-wallpaper")?;
+    tiff_image
+        .encoder()
+        .write_tag(tiff::tags::Tag::Software, "dots-wallpaper")?;
+    tiff_image
+        .encoder()
+        .write_tag(tiff::tags::Tag::ImageDescription, description.as_str())?;
+
+    tiff_image.write_data(image.as_raw())?;
+    Ok(())
+}
+
+/// A bounding box of pixels under construction by the median-cut quantizer.
+struct ColorBox {
+    pixels: Vec<[u8; 3]>,
+}
+
+impl ColorBox {
+    /// The spread between the smallest and largest value present in `channel` (0=R, 1=G, 2=B).
+    fn channel_extent(&self, channel: usize) -> u8 {
+        let (min, max) = self
+            .pixels
+            .iter()
+            .fold((u8::MAX, u8::MIN), |(min, max), p| {
+                (min.min(p[channel]), max.max(p[channel]))
+            });
+        max - min
+    }
+
+    /// The channel with the greatest spread, which median-cut splits along.
+    fn longest_channel(&self) -> usize {
+        (0..3).max_by_key(|&channel| self.channel_extent(channel)).unwrap()
+    }
+
+    /// The per-channel mean of every pixel in the box; this becomes its palette entry.
+    fn average_color(&self) -> [u8; 3] {
+        let count = self.pixels.len().max(1) as u64;
+        let mut sums = [0u64; 3];
+        for pixel in &self.pixels {
+            for (sum, &channel) in sums.iter_mut().zip(pixel.iter()) {
+                *sum += channel as u64;
+            }
+        }
+        [
+            (sums[0] / count) as u8,
+            (sums[1] / count) as u8,
+            (sums[2] / count) as u8,
+        ]
+    }
+
+    /// Sorts the box's pixels along its longest channel and splits them at the median.
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let channel = self.longest_channel();
+        self.pixels.sort_unstable_by_key(|p| p[channel]);
+        let second_half = self.pixels.split_off(self.pixels.len() / 2);
+        (ColorBox { pixels: self.pixels }, ColorBox { pixels: second_half })
+    }
+}
+
+/// Reduces `pixels` to at most `max_colors` representative colors via median-cut
+/// quantization: repeatedly split the box with the largest channel extent at its
+/// median until there are enough boxes, then average each box's pixels.
+/// If there are no more unique colors than `max_colors`, or `max_colors` is 0, the
+/// sorted set of unique colors is returned unchanged.
+fn median_cut_quantize(pixels: &[[u8; 3]], max_colors: usize) -> Vec<[u8; 3]> {
+    let mut unique_colors: Vec<[u8; 3]> = pixels.to_vec();
+    unique_colors.sort_unstable();
+    unique_colors.dedup();
+    if unique_colors.len() <= max_colors || max_colors == 0 {
+        return unique_colors;
+    }
+
+    let mut boxes = vec![ColorBox { pixels: pixels.to_vec() }];
+    while boxes.len() < max_colors {
+        let Some((split_index, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.pixels.len() > 1)
+            .max_by_key(|(_, b)| b.channel_extent(b.longest_channel()))
+        else {
+            break;
+        };
+        let (first, second) = boxes.remove(split_index).split();
+        boxes.push(first);
+        boxes.push(second);
+    }
+
+    boxes.iter().map(ColorBox::average_color).collect()
+}
+
+/// Linear scan for the palette entry closest to `color` by squared Euclidean distance.
+fn nearest_palette_index(color: [u8; 3], palette: &[[u8; 3]]) -> u8 {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, candidate)| {
+            let dr = color[0] as i32 - candidate[0] as i32;
+            let dg = color[1] as i32 - candidate[1] as i32;
+            let db = color[2] as i32 - candidate[2] as i32;
+            dr * dr + dg * dg + db * db
+        })
+        .map(|(index, _)| index as u8)
+        .unwrap_or(0)
+}
+
+/// Number of slots in the palette lookup cache; deliberately prime to spread packed
+/// 24-bit RGB keys evenly under linear probing.
+const PALETTE_CACHE_SIZE: usize = 1409;
+
+/// An open-addressed cache mapping packed RGB colors to their nearest palette index,
+/// so repeated colors in the source image only pay the O(palette size) distance scan once
+/// while the table has free slots; once it is full, uncached colors are scanned on every lookup.
+struct PaletteCache {
+    slots: Vec<Option<(u32, u8)>>,
+}
+
+impl PaletteCache {
+    fn new() -> Self {
+        PaletteCache {
+            slots: vec![None; PALETTE_CACHE_SIZE],
+        }
+    }
+
+    /// Looks up `color`'s nearest palette index, computing and caching it on a miss.
+    fn nearest_index(&mut self, color: [u8; 3], palette: &[[u8; 3]]) -> u8 {
+        let key = ((color[0] as u32) << 16) | ((color[1] as u32) << 8) | color[2] as u32;
+        let mut slot = key as usize % PALETTE_CACHE_SIZE;
+        for _ in 0..PALETTE_CACHE_SIZE {
+            match self.slots[slot] {
+                Some((cached_key, index)) if cached_key == key => return index,
+                Some(_) => slot = (slot + 1) % PALETTE_CACHE_SIZE,
+                None => {
+                    let index = nearest_palette_index(color, palette);
+                    self.slots[slot] = Some((key, index));
+                    return index;
+                }
+            }
+        }
+        // The cache is saturated with collisions; fall back to a direct scan.
+        nearest_palette_index(color, palette)
+    }
+}
+
+/// Quantizes `image` to at most `max_colors` colors, returning the palette and a
+/// per-pixel index buffer (in row-major order) referencing it.
+fn quantize_image(image: &RgbImage, max_colors: usize) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let pixels: Vec<[u8; 3]> = image.pixels().map(|p| p.0).collect();
+    let palette = median_cut_quantize(&pixels, max_colors);
+
+    let mut cache = PaletteCache::new();
+    let indices = pixels
+        .iter()
+        .map(|&color| cache.nearest_index(color, &palette))
+        .collect();
+
+    (palette, indices)
+}
+
+/// The smallest PNG bit depth that can represent a palette of `palette_len` colors.
+fn indexed_bit_depth(palette_len: usize) -> png::BitDepth {
+    match palette_len {
+        0..=2 => png::BitDepth::One,
+        3..=4 => png::BitDepth::Two,
+        5..=16 => png::BitDepth::Four,
+        _ => png::BitDepth::Eight,
+    }
+}
+
+/// Packs one-index-per-byte pixel data into the PNG sub-byte row format for `bit_depth`
+/// (MSB-first, each row padded to a whole byte), or returns it unpacked for `Eight`.
+fn pack_indices(indices: &[u8], width: u32, bit_depth: png::BitDepth) -> Vec<u8> {
+    let bits: u8 = match bit_depth {
+        png::BitDepth::One => 1,
+        png::BitDepth::Two => 2,
+        png::BitDepth::Four => 4,
+        _ => return indices.to_vec(),
+    };
+    let mask: u8 = (1 << bits) - 1;
+
+    let width = width as usize;
+    let row_bytes = (width * bits as usize).div_ceil(8);
+    let mut packed = Vec::with_capacity(row_bytes * indices.len() / width.max(1));
+    for row in indices.chunks(width) {
+        let mut byte = 0u8;
+        let mut bits_in_byte = 0u8;
+        for &index in row {
+            byte = (byte << bits) | (index & mask);
+            bits_in_byte += bits;
+            if bits_in_byte == 8 {
+                packed.push(byte);
+                byte = 0;
+                bits_in_byte = 0;
+            }
+        }
+        if bits_in_byte > 0 {
+            byte <<= 8 - bits_in_byte;
+            packed.push(byte);
+        }
+    }
+    packed
+}
+
+/// The pixel data and encoding parameters needed to produce a PNG, independent of
+/// whether the final bytes come from the straightforward encoder or the optimization
+/// pass below.
+struct PngImageData<'a> {
+    width: u32,
+    height: u32,
+    color_type: png::ColorType,
+    bit_depth: png::BitDepth,
+    palette: Option<Vec<u8>>,
+    pixel_bytes: &'a [u8],
+}
+
+/// Encodes `data` into an in-memory PNG, using the given filter/compression choices.
+fn encode_png(
+    data: &PngImageData,
+    filter: Option<png::FilterType>,
+    adaptive_filter: png::AdaptiveFilterType,
+    compression: png::Compression,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, data.width, data.height);
+        encoder.set_color(data.color_type);
+        encoder.set_depth(data.bit_depth);
+        if let Some(palette) = &data.palette {
+            encoder.set_palette(palette.clone());
+        }
+        encoder.set_compression(compression);
+        if let Some(filter) = filter {
+            encoder.set_filter(filter);
+        }
+        encoder.set_adaptive_filter(adaptive_filter);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(data.pixel_bytes)?;
+    }
+    Ok(bytes)
+}
+
+/// Which deflate effort to spend encoding a PNG's IDAT stream; higher `--optimize`
+/// levels trade encode time for a smaller file.
+enum Deflater {
+    Standard,
+    HighCompression,
+}
+
+impl Deflater {
+    fn for_level(level: u8) -> Self {
+        if level >= 5 {
+            Deflater::HighCompression
+        } else {
+            Deflater::Standard
+        }
+    }
+
+    fn compression(&self) -> png::Compression {
+        match self {
+            Deflater::Standard => png::Compression::Default,
+            Deflater::HighCompression => png::Compression::Best,
+        }
+    }
+}
+
+/// Lossless PNG optimization: tries each fixed filter strategy (None/Sub/Up/Average/
+/// Paeth) plus the adaptive per-row minimum-sum-of-absolute-differences heuristic, and
+/// keeps whichever produces the smallest encoded (deflated) byte stream. Pixels are
+/// never touched, only how they're filtered and compressed.
+fn optimize_png_bytes(data: &PngImageData, optimize_level: u8) -> Result<Vec<u8>, Box<dyn Error>> {
+    let compression = Deflater::for_level(optimize_level).compression();
+
+    let mut best = encode_png(data, None, png::AdaptiveFilterType::Adaptive, compression)?;
+    for filter in [
+        png::FilterType::NoFilter,
+        png::FilterType::Sub,
+        png::FilterType::Up,
+        png::FilterType::Avg,
+        png::FilterType::Paeth,
+    ] {
+        let candidate = encode_png(data, Some(filter), png::AdaptiveFilterType::NonAdaptive, compression)?;
+        if candidate.len() < best.len() {
+            best = candidate;
+        }
+    }
+
+    Ok(best)
+}
+
+/// Writes a PNG, running it through `optimize_png_bytes` first when `optimize_level`
+/// is set.
+fn write_png(data: &PngImageData, output_path: &str, optimize_level: Option<u8>) -> Result<(), Box<dyn Error>> {
+    let bytes = match optimize_level {
+        Some(level) => optimize_png_bytes(data, level)?,
+        None => encode_png(data, None, png::AdaptiveFilterType::NonAdaptive, png::Compression::Default)?,
+    };
+    std::fs::write(output_path, bytes)?;
+    Ok(())
+}
+
+/// True if any pixel's channels differ, i.e. the image isn't already grayscale.
+fn has_color(image: &RgbImage) -> bool {
+    image.pixels().any(|p| p[0] != p[1] || p[1] != p[2])
+}
+
+/// Saves an image whose subpixels are `u8`, driving a quality-parameterized JPEG
+/// encoder when the output is `.jpg`/`.jpeg` and `quality` is set; otherwise falls
+/// back to `image`'s normal format detection.
+fn save_depth_image<P>(
+    output_path: &str,
+    image: &ImageBuffer<P, Vec<u8>>,
+    quality: Option<u8>,
+    color_type: image::ExtendedColorType,
+) -> Result<(), Box<dyn Error>>
+where
+    P: Pixel<Subpixel = u8> + image::PixelWithColorType,
+{
+    let lower_output_path = output_path.to_lowercase();
+    let is_jpeg = lower_output_path.ends_with(".jpg") || lower_output_path.ends_with(".jpeg");
+
+    if is_jpeg {
+        if let Some(quality) = quality {
+            let file = std::fs::File::create(output_path)?;
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(file, quality);
+            encoder.write_image(image.as_raw(), image.width(), image.height(), color_type)?;
+            return Ok(());
+        }
+    }
+
+    image.save(output_path)?;
+    Ok(())
+}
+
+/// Saves an 8-bit RGB canvas, applying the standard/exposed output controls in order:
+///
+/// * `grayscale` - Writes the canvas as 8-bit luma instead of RGB. `has_color` is only
+///   consulted to pick the log message; an already-gray composite still goes through
+///   `to_luma8` (numerically a no-op) so the output file is still encoded as `L8`.
+/// * `palette_size` - Quantizes the (still-color) canvas to an indexed PNG, at the
+///   smallest bit depth (`indexed_bit_depth`) that fits the resulting palette.
+/// * `optimize_level` - Runs the lossless filter/compression search when writing a `.png`.
+/// * `quality` - Drives a quality-parameterized JPEG encoder when writing a `.jpg`/`.jpeg`.
+///
+/// `grayscale` takes priority over `palette_size`/`optimize_level`, since those are
+/// color-quantization and PNG-specific concerns that don't apply to a luma image.
+fn save_rgb_output(
+    output_path: &str,
+    image: &RgbImage,
+    palette_size: Option<u16>,
+    optimize_level: Option<u8>,
+    grayscale: bool,
+    quality: Option<u8>,
+) -> Result<(), Box<dyn Error>> {
+    if grayscale {
+        if has_color(image) {
+            println!("Converting composite to grayscale.");
+        } else {
+            println!("Composite is already grayscale; writing as luma.");
+        }
+        let luma = image::DynamicImage::ImageRgb8(image.clone()).to_luma8();
+        return save_depth_image(output_path, &luma, quality, image::ExtendedColorType::L8);
+    }
+
+    let is_png = output_path.to_lowercase().ends_with(".png");
+
+    match palette_size {
+        Some(max_colors) => {
+            let (palette, indices) = quantize_image(image, max_colors as usize);
+            let mut flat_palette = Vec::with_capacity(palette.len() * 3);
+            for color in &palette {
+                flat_palette.extend_from_slice(color);
+            }
+            let bit_depth = indexed_bit_depth(palette.len());
+            let packed_indices = pack_indices(&indices, image.width(), bit_depth);
+            let data = PngImageData {
+                width: image.width(),
+                height: image.height(),
+                color_type: png::ColorType::Indexed,
+                bit_depth,
+                palette: Some(flat_palette),
+                pixel_bytes: &packed_indices,
+            };
+            write_png(&data, output_path, if is_png { optimize_level } else { None })
+        }
+        None if is_png => {
+            let data = PngImageData {
+                width: image.width(),
+                height: image.height(),
+                color_type: png::ColorType::Rgb,
+                bit_depth: png::BitDepth::Eight,
+                palette: None,
+                pixel_bytes: image.as_raw(),
+            };
+            write_png(&data, output_path, optimize_level)
+        }
+        None => save_depth_image(output_path, image, quality, image::ExtendedColorType::Rgb8),
+    }
+}
+
+/// Groups the optional, CLI-exposed knobs that control how the generated wallpaper is
+/// produced and encoded, so `create_angled_strip_wallpaper` takes one options value
+/// instead of a growing list of positional flags.
+///
+/// All fields default to "do the plain thing": no thread cap, no quantization, no
+/// optimization pass, no grayscale conversion, no quality override.
+#[derive(Default)]
+struct OutputOptions {
+    /// An optional maximum number of rayon worker threads to use.
+    thread_cap: Option<usize>,
+    /// When set, the standard (non-HDR, non-TIFF) output is quantized to at most this
+    /// many colors and written as an indexed PNG, packed at the smallest bit depth
+    /// (1/2/4/8) that fits the resulting palette.
+    palette_size: Option<u16>,
+    /// When set and the output is a `.png`, runs a lossless filter/compression search
+    /// (see `optimize_png_bytes`) before writing; higher values spend more effort on
+    /// the deflate pass.
+    optimize_level: Option<u8>,
+    /// When true, the standard (non-HDR, non-TIFF) output is converted to 8-bit luma
+    /// after compositing; an already-gray composite is still written as luma.
+    grayscale: bool,
+    /// When set and the output is a `.jpg`/`.jpeg`, drives a quality-parameterized
+    /// JPEG encoder instead of the default one.
+    quality: Option<u8>,
+}
+
 /// Creates a composite wallpaper by combining angled strips from multiple images without distortion.
 ///
 /// This function operates by first resizing each source image to the full target resolution.
@@ -11,12 +603,26 @@ use std::process;
 /// location (acting like a mask). It then copies the pixel from the chosen source image
 /// directly, ensuring the source images are never warped or sheared.
 ///
+/// Loading/resizing the sources and compositing the canvas are both embarrassingly
+/// parallel, so both stages run on a rayon thread pool. Set `options.thread_cap` to bound how
+/// many threads that pool may use (e.g. to keep CI runners or memory-constrained
+/// machines predictable); `None` lets rayon pick based on the available cores.
+///
+/// When `output_path` ends in `.exr`, the whole pipeline runs in `Rgb<f32>` instead of
+/// 8-bit `Rgb<u8>`, so HDR sources are never clamped by an intermediate `to_rgb8()`
+/// conversion; the result is written with the `exr` crate instead of `image`'s encoders.
+///
+/// When `output_path` ends in `.tif`/`.tiff`, the composite is written as a TIFF whose
+/// IFD tags record the angle and the ordered list of source paths, so the file is
+/// self-documenting about how it was produced.
+///
 /// # Arguments
 ///
 /// * `output_path` - The path to save the final generated wallpaper.
 /// * `resolution` - A tuple `(width, height)` for the output wallpaper.
 /// * `angle_degrees` - The angle of the dividing slices in degrees. 0 is vertical.
 /// * `wallpaper_paths` - A slice of strings representing the paths to the input images.
+/// * `options` - The CLI-exposed output controls; see `OutputOptions`.
 ///
 /// # Returns
 ///
@@ -26,70 +632,20 @@ fn create_angled_strip_wallpaper(
     resolution: (u32, u32),
     angle_degrees: f32,
     wallpaper_paths: &[String],
+    options: OutputOptions,
 ) -> Result<(), Box<dyn Error>> {
     let (width, height) = resolution;
+    let lower_output_path = output_path.to_lowercase();
+    let is_hdr = lower_output_path.ends_with(".exr");
+    let is_tiff = lower_output_path.ends_with(".tif") || lower_output_path.ends_with(".tiff");
 
-    // --- Step 1: Load and Resize All Images to Full Target Resolution ---
-
-    let resized_images: Vec<RgbImage> = wallpaper_paths
-        .iter()
-        .filter_map(|path| {
-            println!("Loading and resizing: {}", path);
-
-            // FIX: Use a nested match to handle different error types explicitly.
-            // This correctly separates the `io::Error` from `ImageReader::open`
-            // from the `ImageError` that can occur during decoding.
-            match ImageReader::open(path) {
-                Ok(reader) => {
-                    match reader.with_guessed_format() {
-                        Ok(guessed_reader) => match guessed_reader.decode() {
-                            Ok(img) => {
-                                // On success, convert to RGB and resize.
-                                let rgb_img = img.to_rgb8();
-                                Some(imageops::resize(
-                                    &rgb_img,
-                                    width,
-                                    height,
-                                    imageops::FilterType::Lanczos3,
-                                ))
-                            }
-                            Err(e) => {
-                                eprintln!("Warning: Skipping {} due to a decode error: {}", path, e);
-                                None
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("Warning: Skipping {} due to a format error: {}", path, e);
-                            None
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Warning: Skipping {} due to an IO error: {}", path, e);
-                    None
-                }
-            }
-        })
-        .collect();
-
-    let num_users = resized_images.len();
-
-    // --- Edge Case Handling ---
-
-    if num_users == 0 {
-        println!("No valid wallpapers provided, creating a black image.");
-        RgbImage::new(width, height).save(output_path)?;
-        return Ok(());
+    let mut pool_builder = rayon::ThreadPoolBuilder::new();
+    if let Some(num_threads) = options.thread_cap {
+        pool_builder = pool_builder.num_threads(num_threads);
     }
+    let pool = pool_builder.build()?;
 
-    if num_users == 1 {
-        println!("Only one wallpaper provided, saving it directly.");
-        resized_images[0].save(output_path)?;
-        println!("Wallpaper successfully saved to {}", output_path);
-        return Ok(());
-    }
-
-    // --- Step 2: Composite the Pre-Resized Images Using an Angled Mask ---
+    // --- Step 2 setup: the angled mask is identical regardless of pixel depth ---
 
     let theta = angle_degrees.to_radians();
     let tan_theta = theta.tan();
@@ -103,36 +659,193 @@ fn create_angled_strip_wallpaper(
     let max_skewed_x = p1.max(p2).max(p3).max(p4);
     let skewed_range = max_skewed_x - min_skewed_x;
 
-    let mut canvas = RgbImage::new(width, height);
+    if is_hdr {
+        // --- Step 1: Load and Resize All Images to Full Target Resolution (f32) ---
+        let loaded = load_and_resize_sources(wallpaper_paths, width, height, &pool, |img| {
+            img.to_rgb32f()
+        });
+        let resized_images: Vec<Rgb32FImage> =
+            loaded.into_iter().map(|(_, img)| img).collect();
+        let num_users = resized_images.len();
+
+        // --- Edge Case Handling ---
+
+        if num_users == 0 {
+            println!("No valid wallpapers provided, creating a black image.");
+            write_hdr_exr(output_path, &Rgb32FImage::new(width, height))?;
+            return Ok(());
+        }
 
-    for y in 0..height {
-        for x in 0..width {
-            let skewed_x = x as f32 - y as f32 * tan_theta;
-            let normalized_progress = (skewed_x - min_skewed_x) / skewed_range;
-            let image_index_float = normalized_progress * num_users as f32;
-            let image_index = (image_index_float.floor() as usize).min(num_users - 1);
-
-            let source_image = &resized_images[image_index];
-            let pixel = source_image.get_pixel(x, y);
-            canvas.put_pixel(x, y, *pixel);
+        if num_users == 1 {
+            println!("Only one wallpaper provided, saving it directly.");
+            write_hdr_exr(output_path, &resized_images[0])?;
+            println!("Wallpaper successfully saved to {}", output_path);
+            return Ok(());
+        }
+
+        // --- Step 2: Composite the Pre-Resized Images Using an Angled Mask ---
+        let canvas = composite_angled_strips(
+            width,
+            height,
+            tan_theta,
+            min_skewed_x,
+            skewed_range,
+            &resized_images,
+            &pool,
+        )?;
+
+        write_hdr_exr(output_path, &canvas)?;
+        println!("Angled wallpaper successfully saved to {}", output_path);
+        return Ok(());
+    }
+
+    // --- Step 1: Load and Resize All Images to Full Target Resolution ---
+    let loaded = load_and_resize_sources(wallpaper_paths, width, height, &pool, |img| img.to_rgb8());
+    let loaded_paths: Vec<String> = loaded.iter().map(|(path, _)| path.clone()).collect();
+    let resized_images: Vec<RgbImage> = loaded.into_iter().map(|(_, img)| img).collect();
+
+    let num_users = resized_images.len();
+
+    if is_tiff {
+        // --- Edge Case Handling ---
+
+        if num_users == 0 {
+            println!("No valid wallpapers provided, creating a black image.");
+            write_tiff_with_provenance(output_path, &RgbImage::new(width, height), angle_degrees, &loaded_paths)?;
+            return Ok(());
+        }
+
+        if num_users == 1 {
+            println!("Only one wallpaper provided, saving it directly.");
+            write_tiff_with_provenance(output_path, &resized_images[0], angle_degrees, &loaded_paths)?;
+            println!("Wallpaper successfully saved to {}", output_path);
+            return Ok(());
         }
+
+        // --- Step 2: Composite the Pre-Resized Images Using an Angled Mask ---
+        let canvas = composite_angled_strips(
+            width,
+            height,
+            tan_theta,
+            min_skewed_x,
+            skewed_range,
+            &resized_images,
+            &pool,
+        )?;
+
+        write_tiff_with_provenance(output_path, &canvas, angle_degrees, &loaded_paths)?;
+        println!("Angled wallpaper successfully saved to {}", output_path);
+        return Ok(());
+    }
+
+    // --- Edge Case Handling ---
+
+    if num_users == 0 {
+        println!("No valid wallpapers provided, creating a black image.");
+        save_rgb_output(
+            output_path,
+            &RgbImage::new(width, height),
+            options.palette_size,
+            options.optimize_level,
+            options.grayscale,
+            options.quality,
+        )?;
+        return Ok(());
+    }
+
+    if num_users == 1 {
+        println!("Only one wallpaper provided, saving it directly.");
+        save_rgb_output(
+            output_path,
+            &resized_images[0],
+            options.palette_size,
+            options.optimize_level,
+            options.grayscale,
+            options.quality,
+        )?;
+        println!("Wallpaper successfully saved to {}", output_path);
+        return Ok(());
     }
 
-    canvas.save(output_path)?;
+    // --- Step 2: Composite the Pre-Resized Images Using an Angled Mask ---
+    let canvas = composite_angled_strips(
+        width,
+        height,
+        tan_theta,
+        min_skewed_x,
+        skewed_range,
+        &resized_images,
+        &pool,
+    )?;
+
+    save_rgb_output(
+        output_path,
+        &canvas,
+        options.palette_size,
+        options.optimize_level,
+        options.grayscale,
+        options.quality,
+    )?;
     println!("Angled wallpaper successfully saved to {}", output_path);
     Ok(())
 }
 
+/// Pulls a `--flag value` pair out of `args`, returning the parsed value and leaving the
+/// remaining positional arguments (e.g. the wallpaper paths) untouched. Exits the process
+/// with a usage error if the flag is present but its value fails to parse, or if the flag
+/// is repeated (which would otherwise leak the second `--flag`/value pair into the
+/// wallpaper paths instead of being rejected as malformed input).
+fn extract_flag<T: std::str::FromStr>(args: &mut Vec<String>, flag: &str) -> Option<T> {
+    let flag_index = args.iter().position(|arg| arg == flag)?;
+    if flag_index + 1 >= args.len() {
+        eprintln!("Error: {} requires a value.", flag);
+        process::exit(1);
+    }
+    let raw_value = args.remove(flag_index + 1);
+    args.remove(flag_index);
+    if args.iter().any(|arg| arg == flag) {
+        eprintln!("Error: {} may only be specified once.", flag);
+        process::exit(1);
+    }
+    Some(raw_value.parse().unwrap_or_else(|_| {
+        eprintln!("Error: Invalid value provided for {}.", flag);
+        process::exit(1);
+    }))
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    let thread_cap: Option<usize> = extract_flag(&mut args, "--threads");
+    let palette_arg: Option<u16> = extract_flag(&mut args, "--palette");
+    if let Some(n) = palette_arg {
+        if n == 0 || n > 256 {
+            eprintln!("Error: --palette must be between 1 and 256.");
+            process::exit(1);
+        }
+    }
+    let optimize_arg: Option<u8> = extract_flag(&mut args, "--optimize");
+    let quality_arg: Option<u8> = extract_flag(&mut args, "--quality");
+    let grayscale_arg = match args.iter().position(|arg| arg == "--grayscale") {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        None => false,
+    };
 
     if args.len() < 4 {
-        eprintln!("Usage: {} <output_path> <width>x<height> <angle_degrees> [wallpaper_path1] ...", args[0]);
-        eprintln!("Example: {} ./output.png 1920x1080 20 ./img1.jpg ./img2.jpg", args[0]);
+        eprintln!("Usage: {} <output_path> <width>x<height> <angle_degrees> [wallpaper_path1] ... [--threads N] [--palette N] [--optimize LEVEL] [--grayscale] [--quality N]", args[0]);
+        eprintln!("Example: {} ./output.png 1920x1080 20 ./img1.jpg ./img2.jpg --threads 4 --palette 64 --optimize 3 --grayscale --quality 85", args[0]);
         process::exit(1);
     }
 
     let output_arg = &args[1];
+    if palette_arg.is_some() && !output_arg.to_lowercase().ends_with(".png") {
+        eprintln!("Error: --palette requires a .png output path.");
+        process::exit(1);
+    }
+
     let resolution_parts: Vec<&str> = args[2].split('x').collect();
     if resolution_parts.len() != 2 {
         eprintln!("Error: Resolution must be in the format <width>x<height>");
@@ -156,7 +869,15 @@ fn main() {
 
     let paths_arg = if args.len() > 4 { &args[4..] } else { &[] };
 
-    if let Err(e) = create_angled_strip_wallpaper(output_arg, resolution_arg, angle_arg, paths_arg) {
+    let options = OutputOptions {
+        thread_cap,
+        palette_size: palette_arg,
+        optimize_level: optimize_arg,
+        grayscale: grayscale_arg,
+        quality: quality_arg,
+    };
+
+    if let Err(e) = create_angled_strip_wallpaper(output_arg, resolution_arg, angle_arg, paths_arg, options) {
         eprintln!("Application error: {}", e);
         process::exit(1);
     }
@@ -204,7 +925,7 @@ mod tests {
         let output_path = dir.path().join("output.png");
         let output_path_str = output_path.to_str().unwrap();
 
-        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &[]).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &[], OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (100, 100));
@@ -222,7 +943,7 @@ mod tests {
         create_dummy_image(&input_path, 200, 200, red);
 
         let paths = vec![input_path.to_str().unwrap().to_string()];
-        create_angled_strip_wallpaper(output_path_str, (100, 50), 0.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (100, 50), 0.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (100, 50));
@@ -247,7 +968,7 @@ mod tests {
             path2.to_str().unwrap().to_string(),
         ];
         
-        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (100, 100));
@@ -273,7 +994,7 @@ mod tests {
             path2.to_str().unwrap().to_string(),
         ];
         
-        create_angled_strip_wallpaper(output_path_str, (100, 100), 45.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (100, 100), 45.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         
@@ -298,7 +1019,7 @@ mod tests {
         ];
 
         // Should succeed and create a black image (no valid images)
-        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (100, 100));
@@ -331,7 +1052,7 @@ mod tests {
         ];
 
         // Should succeed and create a black image (no valid images)
-        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (100, 100));
@@ -370,7 +1091,7 @@ mod tests {
             bmp_path.to_str().unwrap().to_string(),
         ];
 
-        create_angled_strip_wallpaper(output_path_str, (150, 150), 0.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (150, 150), 0.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (150, 150));
@@ -400,7 +1121,7 @@ mod tests {
             rect_path.to_str().unwrap().to_string(),
         ];
 
-        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (100, 100));
@@ -419,7 +1140,7 @@ mod tests {
 
         let paths = vec![large_path.to_str().unwrap().to_string()];
 
-        create_angled_strip_wallpaper(output_path_str, (200, 200), 0.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (200, 200), 0.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (200, 200));
@@ -439,7 +1160,7 @@ mod tests {
         let paths = vec![rgba_path.to_str().unwrap().to_string()];
 
         // Should handle transparency by converting to RGB
-        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (100, 100));
@@ -462,7 +1183,7 @@ mod tests {
             image_path.to_str().unwrap().to_string(),
         ];
 
-        create_angled_strip_wallpaper(output_path_str, (150, 150), 0.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (150, 150), 0.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (150, 150));
@@ -489,7 +1210,7 @@ mod tests {
             "/non/existent.png".to_string(),            // Non-existent third
         ];
 
-        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (100, 100), 0.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (100, 100));
@@ -518,15 +1239,378 @@ mod tests {
             blue_path.to_str().unwrap().to_string(),
         ];
 
-        create_angled_strip_wallpaper(output_path_str, (300, 100), 0.0, &paths).unwrap();
+        create_angled_strip_wallpaper(output_path_str, (300, 100), 0.0, &paths, OutputOptions::default()).unwrap();
 
         let output_img = image::open(&output_path).unwrap().to_rgb8();
         assert_eq!(output_img.dimensions(), (300, 100));
         
         // Check that ordering is preserved (left to right for 0-degree angle)
         assert_eq!(*output_img.get_pixel(50, 50), Rgb([255, 0, 0]));  // Left: red
-        assert_eq!(*output_img.get_pixel(150, 50), Rgb([0, 255, 0])); // Middle: green  
+        assert_eq!(*output_img.get_pixel(150, 50), Rgb([0, 255, 0])); // Middle: green
         assert_eq!(*output_img.get_pixel(250, 50), Rgb([0, 0, 255])); // Right: blue
     }
+
+    // Reads back an EXR file written by `write_hdr_exr` into a flat row-major RGB buffer.
+    // The pixel storage carries its own row width alongside the buffer since the
+    // per-pixel setter below isn't otherwise given the layer's resolution.
+    fn read_exr_rgb(path: &std::path::Path) -> (usize, usize, Vec<(f32, f32, f32)>) {
+        let image = exr::prelude::read_first_rgba_layer_from_file(
+            path,
+            |resolution, _channels| (resolution.0, vec![(0f32, 0f32, 0f32); resolution.area()]),
+            |(width, pixels), position, (r, g, b, _a): (f32, f32, f32, f32)| {
+                pixels[position.1 * *width + position.0] = (r, g, b);
+            },
+        )
+        .unwrap();
+        let height = image.layer_data.size.1;
+        let (width, pixels) = image.layer_data.channel_data.pixels;
+        (width, height, pixels)
+    }
+
+    #[test]
+    fn test_exr_output_preserves_float_precision_and_ordering() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.exr");
+
+        let red_path = dir.path().join("red.png");
+        let green_path = dir.path().join("green.png");
+        create_dummy_image(&red_path, 100, 100, Rgb([255, 0, 0]));
+        create_dummy_image(&green_path, 100, 100, Rgb([0, 255, 0]));
+
+        let paths = vec![
+            red_path.to_str().unwrap().to_string(),
+            green_path.to_str().unwrap().to_string(),
+        ];
+
+        create_angled_strip_wallpaper(
+            output_path.to_str().unwrap(),
+            (200, 100),
+            0.0,
+            &paths,
+            OutputOptions::default(),
+        )
+        .unwrap();
+
+        let (width, height, pixels) = read_exr_rgb(&output_path);
+        assert_eq!((width, height), (200, 100));
+        assert_eq!(pixels[50 * width + 50], (1.0, 0.0, 0.0));
+        assert_eq!(pixels[50 * width + 150], (0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_exr_single_image_saved_directly() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.exr");
+
+        let blue_path = dir.path().join("blue.png");
+        create_dummy_image(&blue_path, 64, 64, Rgb([0, 0, 255]));
+        let paths = vec![blue_path.to_str().unwrap().to_string()];
+
+        create_angled_strip_wallpaper(
+            output_path.to_str().unwrap(),
+            (64, 64),
+            0.0,
+            &paths,
+            OutputOptions::default(),
+        )
+        .unwrap();
+
+        let (width, height, pixels) = read_exr_rgb(&output_path);
+        assert_eq!((width, height), (64, 64));
+        assert_eq!(pixels[32 * width + 32], (0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_exr_no_images_creates_black_canvas() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.exr");
+
+        create_angled_strip_wallpaper(
+            output_path.to_str().unwrap(),
+            (32, 32),
+            0.0,
+            &[],
+            OutputOptions::default(),
+        )
+        .unwrap();
+
+        let (width, height, pixels) = read_exr_rgb(&output_path);
+        assert_eq!((width, height), (32, 32));
+        assert!(pixels.iter().all(|&(r, g, b)| r == 0.0 && g == 0.0 && b == 0.0));
+    }
+
+    #[test]
+    fn test_tiff_output_embeds_provenance() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.tiff");
+        let output_path_str = output_path.to_str().unwrap();
+
+        let red_path = dir.path().join("red.png");
+        let green_path = dir.path().join("green.png");
+        create_dummy_image(&red_path, 100, 100, Rgb([255, 0, 0]));
+        create_dummy_image(&green_path, 100, 100, Rgb([0, 255, 0]));
+
+        let paths = vec![
+            red_path.to_str().unwrap().to_string(),
+            green_path.to_str().unwrap().to_string(),
+        ];
+
+        create_angled_strip_wallpaper(output_path_str, (100, 100), 30.0, &paths, OutputOptions::default()).unwrap();
+
+        let file = std::fs::File::open(&output_path).unwrap();
+        let mut decoder = tiff::decoder::Decoder::new(file).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (100, 100));
+
+        let description = decoder
+            .get_tag_ascii_string(tiff::tags::Tag::ImageDescription)
+            .unwrap();
+        assert!(description.contains("angle=30"));
+        assert!(description.contains(red_path.to_str().unwrap()));
+        assert!(description.contains(green_path.to_str().unwrap()));
+    }
+
+    #[test]
+    fn test_palette_quantizes_to_requested_color_count() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.png");
+        let output_path_str = output_path.to_str().unwrap();
+
+        let red_path = dir.path().join("red.png");
+        let green_path = dir.path().join("green.png");
+        let blue_path = dir.path().join("blue.png");
+        create_dummy_image(&red_path, 100, 100, Rgb([255, 0, 0]));
+        create_dummy_image(&green_path, 100, 100, Rgb([0, 255, 0]));
+        create_dummy_image(&blue_path, 100, 100, Rgb([0, 0, 255]));
+
+        let paths = vec![
+            red_path.to_str().unwrap().to_string(),
+            green_path.to_str().unwrap().to_string(),
+            blue_path.to_str().unwrap().to_string(),
+        ];
+
+        create_angled_strip_wallpaper(
+            output_path_str,
+            (300, 100),
+            0.0,
+            &paths,
+            OutputOptions {
+                palette_size: Some(2),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let output_img = image::open(&output_path).unwrap().to_rgb8();
+        assert_eq!(output_img.dimensions(), (300, 100));
+
+        let unique_colors: std::collections::HashSet<[u8; 3]> =
+            output_img.pixels().map(|p| p.0).collect();
+        assert!(unique_colors.len() <= 2);
+    }
+
+    #[test]
+    fn test_palette_packs_to_smallest_fitting_bit_depth() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.png");
+        let output_path_str = output_path.to_str().unwrap();
+
+        let red_path = dir.path().join("red.png");
+        let green_path = dir.path().join("green.png");
+        create_dummy_image(&red_path, 100, 100, Rgb([255, 0, 0]));
+        create_dummy_image(&green_path, 100, 100, Rgb([0, 255, 0]));
+
+        let paths = vec![
+            red_path.to_str().unwrap().to_string(),
+            green_path.to_str().unwrap().to_string(),
+        ];
+
+        create_angled_strip_wallpaper(
+            output_path_str,
+            (200, 100),
+            0.0,
+            &paths,
+            OutputOptions {
+                palette_size: Some(2),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let file = std::fs::File::open(&output_path).unwrap();
+        let reader = png::Decoder::new(file).read_info().unwrap();
+        assert_eq!(reader.info().bit_depth, png::BitDepth::One);
+
+        // The pixel values themselves must still round-trip correctly through the
+        // packed bit depth, independent of what depth was chosen to store them.
+        let output_img = image::open(&output_path).unwrap().to_rgb8();
+        let unique_colors: std::collections::HashSet<[u8; 3]> =
+            output_img.pixels().map(|p| p.0).collect();
+        assert!(unique_colors.len() <= 2);
+        assert_eq!(*output_img.get_pixel(50, 50), Rgb([255, 0, 0]));
+        assert_eq!(*output_img.get_pixel(150, 50), Rgb([0, 255, 0]));
+    }
+
+    #[test]
+    fn test_palette_collapses_to_exact_unique_colors() {
+        let pixels = vec![[10u8, 20, 30]; 50];
+        let palette = median_cut_quantize(&pixels, 16);
+        assert_eq!(palette, vec![[10, 20, 30]]);
+    }
+
+    #[test]
+    fn test_optimize_preserves_pixels() {
+        let dir = tempdir().unwrap();
+        let plain_path = dir.path().join("plain.png");
+        let optimized_path = dir.path().join("optimized.png");
+
+        let red_path = dir.path().join("red.png");
+        let green_path = dir.path().join("green.png");
+        create_dummy_image(&red_path, 100, 100, Rgb([255, 0, 0]));
+        create_dummy_image(&green_path, 100, 100, Rgb([0, 255, 0]));
+
+        let paths = vec![
+            red_path.to_str().unwrap().to_string(),
+            green_path.to_str().unwrap().to_string(),
+        ];
+
+        create_angled_strip_wallpaper(
+            plain_path.to_str().unwrap(),
+            (100, 100),
+            30.0,
+            &paths,
+            OutputOptions::default(),
+        )
+        .unwrap();
+        create_angled_strip_wallpaper(
+            optimized_path.to_str().unwrap(),
+            (100, 100),
+            30.0,
+            &paths,
+            OutputOptions {
+                optimize_level: Some(6),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let plain_img = image::open(&plain_path).unwrap().to_rgb8();
+        let optimized_img = image::open(&optimized_path).unwrap().to_rgb8();
+        assert_eq!(plain_img, optimized_img);
+    }
+
+    #[test]
+    fn test_grayscale_writes_luma_color_type() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.png");
+        let output_path_str = output_path.to_str().unwrap();
+
+        let red_path = dir.path().join("red.png");
+        let green_path = dir.path().join("green.png");
+        create_dummy_image(&red_path, 100, 100, Rgb([200, 40, 40]));
+        create_dummy_image(&green_path, 100, 100, Rgb([40, 200, 40]));
+
+        let paths = vec![
+            red_path.to_str().unwrap().to_string(),
+            green_path.to_str().unwrap().to_string(),
+        ];
+
+        create_angled_strip_wallpaper(
+            output_path_str,
+            (200, 100),
+            0.0,
+            &paths,
+            OutputOptions {
+                grayscale: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let decoded = image::open(&output_path).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::L8);
+        for pixel in decoded.to_rgb8().pixels() {
+            assert_eq!(pixel[0], pixel[1]);
+            assert_eq!(pixel[1], pixel[2]);
+        }
+    }
+
+    #[test]
+    fn test_grayscale_on_already_gray_sources_still_writes_luma_color_type() {
+        let dir = tempdir().unwrap();
+        let output_path = dir.path().join("output.png");
+        let output_path_str = output_path.to_str().unwrap();
+
+        let dark_path = dir.path().join("dark.png");
+        let light_path = dir.path().join("light.png");
+        create_dummy_image(&dark_path, 100, 100, Rgb([60, 60, 60]));
+        create_dummy_image(&light_path, 100, 100, Rgb([200, 200, 200]));
+
+        let paths = vec![
+            dark_path.to_str().unwrap().to_string(),
+            light_path.to_str().unwrap().to_string(),
+        ];
+
+        create_angled_strip_wallpaper(
+            output_path_str,
+            (200, 100),
+            0.0,
+            &paths,
+            OutputOptions {
+                grayscale: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let decoded = image::open(&output_path).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::L8);
+    }
+
+    #[test]
+    fn test_quality_changes_encoded_jpeg_size() {
+        let dir = tempdir().unwrap();
+        let low_quality_path = dir.path().join("low.jpg");
+        let high_quality_path = dir.path().join("high.jpg");
+
+        let red_path = dir.path().join("red.png");
+        let green_path = dir.path().join("green.png");
+        create_dummy_image(&red_path, 150, 150, Rgb([180, 60, 20]));
+        create_dummy_image(&green_path, 150, 150, Rgb([20, 60, 180]));
+
+        let paths = vec![
+            red_path.to_str().unwrap().to_string(),
+            green_path.to_str().unwrap().to_string(),
+        ];
+
+        create_angled_strip_wallpaper(
+            low_quality_path.to_str().unwrap(),
+            (150, 150),
+            20.0,
+            &paths,
+            OutputOptions {
+                quality: Some(5),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        create_angled_strip_wallpaper(
+            high_quality_path.to_str().unwrap(),
+            (150, 150),
+            20.0,
+            &paths,
+            OutputOptions {
+                quality: Some(95),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let low_size = std::fs::metadata(&low_quality_path).unwrap().len();
+        let high_size = std::fs::metadata(&high_quality_path).unwrap().len();
+        assert!(
+            high_size > low_size,
+            "expected higher JPEG quality to encode larger than lower quality"
+        );
+    }
 }
 